@@ -14,11 +14,23 @@
 // limitations under the License.
 //
 
-use std::fs;
+use std::{fs, sync::Arc};
 use colored::Colorize;
-use http_auth_basic::Credentials;
-use reqwest::header::{HeaderMap, AUTHORIZATION};
+use semver::Version;
 use stof::{pkg::PKG, SDoc, SField, SVal};
+use tokio::{sync::Semaphore, task::JoinSet};
+use crate::auth;
+use crate::lock::{sha256_hex, StofLock};
+use crate::resolve::{self, Entry, SharedResolver};
+
+
+/// Shared state threaded through one transitive install: the resolver that de-duplicates
+/// and version-checks packages, and a semaphore bounding how many downloads run at once.
+#[derive(Clone)]
+struct InstallCtx {
+    resolver: SharedResolver,
+    jobs: Arc<Semaphore>,
+}
 
 
 /// Remove a stof package from this workspace.
@@ -28,8 +40,54 @@ pub(crate) async fn remove_package(pkg_dir_path: &str) -> bool {
 }
 
 
-/// Publish a stof package to registries.
-pub(crate) async fn add_package(pkg_dir: &str, download_pkg: &str, registry: Option<String>, dependency: bool, username: Option<String>, password: Option<String>) {
+/// Add a stof package (and its transitive dependencies) to this workspace.
+/// This is the entry point - it starts a fresh dependency resolution for the install.
+/// `jobs` bounds how many dependency downloads run concurrently (default: available parallelism).
+pub(crate) async fn add_package(pkg_dir: &str, download_pkg: &str, registry: Option<String>, dependency: bool, username: Option<String>, password: Option<String>, jobs: Option<usize>) {
+    let jobs = jobs.unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4));
+    let ctx = InstallCtx {
+        resolver: resolve::new_shared(),
+        jobs: Arc::new(Semaphore::new(jobs.max(1))),
+    };
+    add_package_resolved(pkg_dir, download_pkg, registry, dependency, username, password, ctx, Vec::new()).await;
+}
+
+
+/// Same as `add_package`, but threads the shared `InstallCtx` and the chain of ancestor
+/// package names on this path (for cycle detection) through the whole transitive install.
+async fn add_package_resolved(pkg_dir: &str, download_pkg: &str, registry: Option<String>, dependency: bool, username: Option<String>, password: Option<String>, ctx: InstallCtx, ancestors: Vec<String>) {
+    let spec = resolve::parse_dep_spec(download_pkg);
+    loop {
+        let mut resolver = ctx.resolver.lock().await;
+        let entry = resolver.enter(&spec.name, &spec.req, &ancestors);
+        match entry {
+            Entry::Cycle => return,
+            Entry::AlreadyResolved => {
+                if dependency {
+                    println!("\t{} {} {}", "...".dimmed(), "already resolved (shared dependency)".purple(), spec.name.blue());
+                }
+                return;
+            },
+            Entry::InFlight(notify) => {
+                // another concurrent task is already fetching this package - wait for it
+                // to finish, then re-check rather than racing a duplicate download. The
+                // waiter is registered before the resolver lock is released, so a
+                // `resolve`/`fail` landing in between can't be missed.
+                let notified = notify.notified();
+                drop(resolver);
+                notified.await;
+                continue;
+            },
+            Entry::Fetch => break,
+        }
+    }
+
+    // Cleared via `ctx.resolver.fail(...)` at the end of every path below that doesn't call
+    // `resolve(...)` - otherwise a registry miss, network error, or bad checksum would leave
+    // this package's in-flight marker set forever, hanging every concurrent sibling awaiting
+    // it via `Entry::InFlight`.
+    let mut settled = false;
+
     let pkg_path = format!("{}/pkg.stof", pkg_dir);
     if let Ok(pkg_doc) = SDoc::file(&pkg_path, "stof") {
         let mut reg = None;
@@ -63,39 +121,101 @@ pub(crate) async fn add_package(pkg_dir: &str, download_pkg: &str, registry: Opt
 
         if let Some(registry) = reg {
             if let Some(url_field) = SField::field(&pkg_doc.graph, "registry.url", '.', Some(&registry)) {
-                let download = download_pkg.trim_start_matches("@").to_owned();
-                let url = format!("{}/registry/{}", url_field.to_string(), download);
-                let client = reqwest::Client::new();
+                let registry_url = url_field.to_string();
+                let download = spec.name.trim_start_matches("@").to_owned();
+                let mut lock = StofLock::load(pkg_dir);
 
-                let mut headers = HeaderMap::new();
-                if username.is_some() && password.is_some() {
-                    let credentials = Credentials::new(&username.clone().unwrap(), &password.clone().unwrap());
-                    headers.insert(AUTHORIZATION, credentials.as_http_header().parse().unwrap());
+                // If a cached unzip already exists and its digest still matches the lock,
+                // skip the network fetch entirely.
+                let outdir = format!("__stof__/{}", download);
+                if let Some(locked) = lock.get(&spec.name) {
+                    if fs::metadata(&outdir).is_ok() {
+                        if let Ok(cached_bytes) = read_pkg_dir_bytes(&outdir) {
+                            if sha256_hex(&cached_bytes) == locked.tree_sha256 {
+                                if let Ok(version) = Version::parse(&locked.version) {
+                                    ctx.resolver.lock().await.resolve(&spec.name, &version);
+                                    settled = true;
+                                }
+                                let mut child_ancestors = ancestors.clone();
+                                child_ancestors.push(spec.name.clone());
+                                add_dependencies(&outdir, pkg_dir, username, password, ctx.clone(), child_ancestors).await;
+                                if dependency {
+                                    println!("\t{} {} {}", "...".dimmed(), "added dependency (cached)".purple(), spec.name.blue());
+                                } else {
+                                    println!("{} {} {}", "added".green(), spec.name.blue(), "(cached)".dimmed());
+                                }
+                                if !settled {
+                                    ctx.resolver.lock().await.fail(&spec.name);
+                                }
+                                return;
+                            }
+                        }
+                    }
                 }
 
+                let permit = ctx.jobs.clone().acquire_owned().await.ok();
+                let url = format!("{}/registry/{}", registry_url, download);
+                let client = reqwest::Client::new();
+                let headers = auth::auth_headers(&registry_url, &username, &password);
+
                 let res = client.get(&url)
                     .headers(headers)
                     .send()
                     .await;
+                drop(permit);
 
                 match res {
                     Ok(response) => {
                         if response.status().is_success() {
+                            let server_checksum = response.headers().get("X-Stof-Checksum").and_then(|v| v.to_str().ok()).map(|s| s.to_owned());
                             if let Ok(bytes) = response.bytes().await {
+                                let digest = sha256_hex(&bytes);
+                                if let Some(expected) = &server_checksum {
+                                    if *expected != digest {
+                                        println!("{}: {} {}", "add package error".red(), spec.name.blue(), "checksum mismatch against the registry's X-Stof-Checksum header - refusing to install tampered or corrupted package".italic().dimmed());
+                                        ctx.resolver.lock().await.fail(&spec.name);
+                                        return;
+                                    }
+                                }
+                                if let Some(locked) = lock.get(&spec.name) {
+                                    if locked.sha256 != digest {
+                                        println!("{}: {} {}", "add package error".red(), spec.name.blue(), "checksum mismatch against stof.lock - refusing to install tampered or corrupted package".italic().dimmed());
+                                        ctx.resolver.lock().await.fail(&spec.name);
+                                        return;
+                                    }
+                                }
+
                                 let pkg_format = PKG::default();
-                                let outdir = pkg_format.unzip_pkg_bytes(download_pkg, &bytes);
-                                add_dependencies(&outdir, pkg_dir, username, password).await;
-                                
+                                let outdir = pkg_format.unzip_pkg_bytes(&spec.name, &bytes);
+
+                                let version = package_version(&outdir);
+                                if let Ok(parsed_version) = Version::parse(&version) {
+                                    ctx.resolver.lock().await.resolve(&spec.name, &parsed_version);
+                                    settled = true;
+                                }
+
+                                // Recorded over the unzipped tree (not the zip bytes in `digest`)
+                                // so a later cache-hit check, which re-hashes `read_pkg_dir_bytes`
+                                // the same way, can actually match.
+                                let tree_digest = read_pkg_dir_bytes(&outdir).map(|b| sha256_hex(&b)).unwrap_or_default();
+                                let dependencies = dependency_names(&outdir);
+                                lock.record(&spec.name, &version, &digest, &tree_digest, &registry_url, dependencies);
+                                lock.save(pkg_dir);
+
+                                let mut child_ancestors = ancestors.clone();
+                                child_ancestors.push(spec.name.clone());
+                                add_dependencies(&outdir, pkg_dir, username, password, ctx.clone(), child_ancestors).await;
+
                                 if dependency {
-                                    println!("\t{} {} {}", "...".dimmed(), "added dependency".purple(), download_pkg.blue());
+                                    println!("\t{} {} {}", "...".dimmed(), "added dependency".purple(), spec.name.blue());
                                 } else {
-                                    println!("{} {}", "added".green(), download_pkg.blue());
+                                    println!("{} {}", "added".green(), spec.name.blue());
                                 }
                             } else {
                                 println!("{}: {}", "publish send error".red(), "could not parse response into bytes".italic().dimmed());
                             }
                         } else {
-                            println!("{}: {} {}", "publish send error".red(), download_pkg.blue(), "does not exist or not authenticated".italic().dimmed());
+                            println!("{}: {} {}", "publish send error".red(), spec.name.blue(), "does not exist or not authenticated".italic().dimmed());
                         }
                     },
                     Err(error) => {
@@ -111,28 +231,85 @@ pub(crate) async fn add_package(pkg_dir: &str, download_pkg: &str, registry: Opt
     } else {
         println!("{}: {}", "add package error".red(), "pkg.stof file not found".italic().dimmed());
     }
+
+    if !settled {
+        ctx.resolver.lock().await.fail(&spec.name);
+    }
 }
 
 
-/// Add dependencies for the newly added package.
-async fn add_dependencies(outdir: &str, pkg_dir: &str, username: Option<String>, password: Option<String>) {
+/// Fetch a package's declared dependencies concurrently (bounded by `ctx.jobs`), each
+/// resolved through the shared `Resolver` so diamonds are de-duplicated and cycles are
+/// broken instead of re-downloading forever. Per-package success/error lines are printed
+/// from within each task so output stays readable under parallelism.
+async fn add_dependencies(outdir: &str, pkg_dir: &str, username: Option<String>, password: Option<String>, ctx: InstallCtx, ancestors: Vec<String>) {
     let added_pkg_path = format!("{}/pkg.stof", outdir);
-    if let Ok(added_pkg_doc) = SDoc::file(&added_pkg_path, "stof") {
-        if let Some(deps_field) = SField::field(&added_pkg_doc.graph, "root.dependencies", '.', None) {
+    let Ok(added_pkg_doc) = SDoc::file(&added_pkg_path, "stof") else { return; };
+    let Some(deps_field) = SField::field(&added_pkg_doc.graph, "root.dependencies", '.', None) else { return; };
+
+    let mut deps: Vec<(String, Option<String>)> = Vec::new();
+    match &deps_field.value {
+        SVal::Array(vals) => {
+            for val in vals {
+                match val {
+                    SVal::String(download_pkg) => deps.push((download_pkg.clone(), None)),
+                    SVal::Object(nref) => {
+                        if let Some(name_field) = SField::field(&added_pkg_doc.graph, "name", '.', Some(nref)) {
+                            let registry = SField::field(&added_pkg_doc.graph, "registry", '.', Some(nref)).map(|f| f.to_string());
+                            deps.push((name_field.to_string(), registry));
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        },
+        _ => {}
+    }
+
+    let mut set = JoinSet::new();
+    for (download_pkg, registry) in deps {
+        let pkg_dir = pkg_dir.to_owned();
+        let username = username.clone();
+        let password = password.clone();
+        let ctx = ctx.clone();
+        let ancestors = ancestors.clone();
+        set.spawn(async move {
+            Box::pin(add_package_resolved(&pkg_dir, &download_pkg, registry, true, username, password, ctx, ancestors)).await;
+        });
+    }
+    while let Some(_res) = set.join_next().await {
+        // errors are already reported from within each task; nothing to aggregate here
+    }
+}
+
+
+/// Read back the "root.version" field of an already-unzipped package, used to populate
+/// the `stof.lock` entry after a fresh download.
+fn package_version(outdir: &str) -> String {
+    let pkg_path = format!("{}/pkg.stof", outdir);
+    if let Ok(pkg_doc) = SDoc::file(&pkg_path, "stof") {
+        if let Some(version_field) = SField::field(&pkg_doc.graph, "root.version", '.', None) {
+            return version_field.to_string();
+        }
+    }
+    String::default()
+}
+
+
+/// Read back the flat list of declared dependency names of an already-unzipped package.
+fn dependency_names(outdir: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let pkg_path = format!("{}/pkg.stof", outdir);
+    if let Ok(pkg_doc) = SDoc::file(&pkg_path, "stof") {
+        if let Some(deps_field) = SField::field(&pkg_doc.graph, "root.dependencies", '.', None) {
             match &deps_field.value {
                 SVal::Array(vals) => {
                     for val in vals {
                         match val {
-                            SVal::String(download_pkg) => {
-                                Box::pin(add_package(pkg_dir, download_pkg, None, true, username.clone(), password.clone())).await;
-                            },
+                            SVal::String(download_pkg) => names.push(download_pkg.clone()),
                             SVal::Object(nref) => {
-                                if let Some(name_field) = SField::field(&added_pkg_doc.graph, "name", '.', Some(nref)) {
-                                    if let Some(registry_field) = SField::field(&added_pkg_doc.graph, "registry", '.', Some(nref)) {
-                                        Box::pin(add_package(pkg_dir, &name_field.to_string(), Some(registry_field.to_string()), true, username.clone(), password.clone())).await;
-                                    } else {
-                                        Box::pin(add_package(pkg_dir, &name_field.to_string(), None, true, username.clone(), password.clone())).await;
-                                    }
+                                if let Some(name_field) = SField::field(&pkg_doc.graph, "name", '.', Some(nref)) {
+                                    names.push(name_field.to_string());
                                 }
                             },
                             _ => {}
@@ -143,4 +320,23 @@ async fn add_dependencies(outdir: &str, pkg_dir: &str, username: Option<String>,
             }
         }
     }
+    names
+}
+
+
+/// Recursively collect the raw bytes of every file in a cached, already-unzipped package
+/// directory, used to recompute a digest for cache-hit verification against `stof.lock`.
+fn read_pkg_dir_bytes(dir: &str) -> std::io::Result<Vec<u8>> {
+    let mut all = Vec::new();
+    let mut entries: Vec<_> = fs::read_dir(dir)?.filter_map(|e| e.ok()).collect();
+    entries.sort_by_key(|e| e.path());
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            all.extend(read_pkg_dir_bytes(path.to_str().unwrap_or_default())?);
+        } else {
+            all.extend(fs::read(&path)?);
+        }
+    }
+    Ok(all)
 }
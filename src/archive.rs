@@ -0,0 +1,42 @@
+//
+// Copyright 2024 Formata, Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::{fs::File, io::BufWriter};
+use flate2::{write::GzEncoder, Compression};
+
+
+/// Build a gzip-compressed tar archive from `files` (the same resolved, filtered file set
+/// `stof pkg --list` would print), for when `--format gzip` is selected instead of the
+/// default zip `.pkg` format - the same store/fast/best trade-off cargo exposes through its
+/// own `GzBuilder`/`Compression` packaging pipeline.
+pub(crate) fn create_gzip_tar(dir: &str, out_path: &str, compression: u32, files: &[(String, u64)]) -> Option<String> {
+    let mut path = out_path.trim_end_matches(".pkg").to_string();
+    if !path.ends_with(".tar.gz") {
+        path = format!("{}.tar.gz", path);
+    }
+
+    let file = File::create(&path).ok()?;
+    let encoder = GzEncoder::new(BufWriter::new(file), Compression::new(compression.min(9)));
+    let mut builder = tar::Builder::new(encoder);
+
+    for (relative, _) in files {
+        let full_path = format!("{}/{}", dir, relative);
+        builder.append_path_with_name(&full_path, relative).ok()?;
+    }
+
+    builder.into_inner().ok()?.finish().ok()?;
+    Some(path)
+}
@@ -0,0 +1,170 @@
+//
+// Copyright 2024 Formata, Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::{collections::HashMap, fs, path::PathBuf};
+use colored::Colorize;
+use http_auth_basic::Credentials;
+use pasetors::{claims::Claims, token::UntrustedToken, version4::V4, Public};
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+
+/// Perms bitfield, shared with the model already used by `remote::set_remote_user`.
+/// 0b001 - read registry, 0b010 - modify registry, 0b100 - exec
+pub(crate) const PERM_READ: i64 = 0b001;
+pub(crate) const PERM_MODIFY: i64 = 0b010;
+pub(crate) const PERM_EXEC: i64 = 0b100;
+
+
+/// A locally stored PASETO access token for a single registry address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct StoredToken {
+    /// PASETO "v4.public...." token, sent back as-is in the `Authorization` header.
+    pub token: String,
+    pub scope: String,
+    pub perms: i64,
+}
+
+/// `~/.stof/credentials` - locally cached tokens, keyed by registry address.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CredentialStore {
+    #[serde(default)]
+    tokens: HashMap<String, StoredToken>,
+}
+impl CredentialStore {
+    fn path() -> Option<PathBuf> {
+        let home = dirs::home_dir()?;
+        Some(home.join(".stof").join("credentials"))
+    }
+
+    fn load() -> Self {
+        if let Some(path) = Self::path() {
+            if let Ok(contents) = fs::read_to_string(&path) {
+                if let Ok(store) = serde_json::from_str(&contents) {
+                    return store;
+                }
+            }
+        }
+        Self::default()
+    }
+
+    fn save(&self) {
+        if let Some(path) = Self::path() {
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            if let Ok(contents) = serde_json::to_string_pretty(self) {
+                let _ = fs::write(&path, contents);
+            }
+        }
+    }
+}
+
+
+/// Exchange basic credentials for a signed PASETO token at `{address}/login`, caching it
+/// locally so subsequent commands send `Authorization: Bearer <token>` instead of a password.
+pub async fn login(address: &str, username: &str, password: &str) -> bool {
+    let url = format!("{}/login", address);
+    let credentials = Credentials::new(username, password);
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, credentials.as_http_header().parse().unwrap());
+
+    let client = reqwest::Client::new();
+    match client.post(&url).headers(headers).send().await {
+        Ok(response) if response.status().is_success() => {
+            if let Ok(token) = response.text().await {
+                if let Some((scope, perms)) = decode_claims(&token) {
+                    let mut store = CredentialStore::load();
+                    store.tokens.insert(address.to_owned(), StoredToken { token, scope, perms });
+                    store.save();
+                    println!("{} {}", "logged in to".green(), address.blue());
+                    return true;
+                }
+            }
+            println!("{}: {}", "login error".red(), "could not parse token response".italic().dimmed());
+            false
+        },
+        Ok(response) => {
+            println!("{}: {} {}", "login error".red(), address.blue(), response.status().as_str().italic().dimmed());
+            false
+        },
+        Err(error) => {
+            println!("{}: {}", "login error".red(), error.to_string().italic().dimmed());
+            false
+        }
+    }
+}
+
+/// Drop the locally cached token for a registry address.
+pub fn logout(address: &str) {
+    let mut store = CredentialStore::load();
+    if store.tokens.remove(address).is_some() {
+        store.save();
+        println!("{} {}", "logged out of".green(), address.blue());
+    } else {
+        println!("{}", "no stored session for that address".italic().dimmed());
+    }
+}
+
+/// Look up the cached token for a registry address, if one was ever issued via `login`.
+pub(crate) fn stored_token(address: &str) -> Option<StoredToken> {
+    CredentialStore::load().tokens.remove(address)
+}
+
+/// Build a bare `Authorization: Bearer <token>` header map for an explicit token, ex. one
+/// read from a `registry.token` field in `pkg.stof`.
+pub(crate) fn bearer(token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = format!("Bearer {}", token).parse() {
+        headers.insert(AUTHORIZATION, value);
+    }
+    headers
+}
+
+/// Build request headers for a registry address: a cached bearer token if `login` was run
+/// against it, falling back to Basic auth from explicit credentials, or empty otherwise.
+pub(crate) fn auth_headers(address: &str, username: &Option<String>, password: &Option<String>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(stored) = stored_token(address) {
+        if let Ok(value) = format!("Bearer {}", stored.token).parse() {
+            headers.insert(AUTHORIZATION, value);
+        }
+        return headers;
+    }
+    if let (Some(username), Some(password)) = (username, password) {
+        let credentials = Credentials::new(username, password);
+        headers.insert(AUTHORIZATION, credentials.as_http_header().parse().unwrap());
+    }
+    headers
+}
+
+/// Check, purely from the locally cached claims, whether the token we hold for `address`
+/// grants the modify bit for `scope` - so a doomed publish fails fast before any bytes ship.
+pub(crate) fn can_modify_scope(address: &str, scope: &str) -> bool {
+    match stored_token(address) {
+        Some(token) => token.perms & PERM_MODIFY != 0 && (token.scope.is_empty() || token.scope == scope),
+        None => true, // no cached token - fall back to whatever Basic auth the server enforces
+    }
+}
+
+/// Decode a PASETO v4 public token's claims without verifying its signature - this CLI
+/// only reads back the claims a trusted registry just handed it, it never mints tokens itself.
+fn decode_claims(token: &str) -> Option<(String, i64)> {
+    let untrusted = UntrustedToken::<Public, V4>::try_from(token).ok()?;
+    let claims: &Claims = untrusted.untrusted_payload_claims();
+    let scope = claims.get_claim("scope")?.as_str()?.to_owned();
+    let perms = claims.get_claim("perms")?.as_i64()?;
+    Some((scope, perms))
+}
@@ -0,0 +1,153 @@
+//
+// Copyright 2024 Formata, Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::collections::HashSet;
+use colored::Colorize;
+use stof::{SDoc, SField, SVal};
+use crate::{license, resolve};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found while verifying a package manifest, modeled on Deno's
+/// `PublishDiagnosticsCollector`: every problem is gathered in one pass rather than
+/// failing on the first one found.
+#[derive(Debug, Clone)]
+pub(crate) struct Diagnostic {
+    pub severity: Severity,
+    pub field_path: String,
+    pub message: String,
+}
+
+/// The full set of diagnostics gathered for one package, ready to print and/or gate a publish.
+#[derive(Debug, Default)]
+pub(crate) struct Diagnostics {
+    pub diagnostics: Vec<Diagnostic>,
+}
+impl Diagnostics {
+    pub(crate) fn error(&mut self, field_path: &str, message: &str) {
+        self.diagnostics.push(Diagnostic { severity: Severity::Error, field_path: field_path.to_owned(), message: message.to_owned() });
+    }
+
+    pub(crate) fn warning(&mut self, field_path: &str, message: &str) {
+        self.diagnostics.push(Diagnostic { severity: Severity::Warning, field_path: field_path.to_owned(), message: message.to_owned() });
+    }
+
+    /// Whether any error-level diagnostic was found - publish should be blocked if so.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    /// Print every diagnostic, errors first, each with the offending field path.
+    pub fn print(&self) {
+        for d in self.diagnostics.iter().filter(|d| d.severity == Severity::Error) {
+            println!("{} {}: {}", "error".red(), d.field_path.blue(), d.message.dimmed());
+        }
+        for d in self.diagnostics.iter().filter(|d| d.severity == Severity::Warning) {
+            println!("{} {}: {}", "warning".yellow(), d.field_path.blue(), d.message.dimmed());
+        }
+        if self.diagnostics.is_empty() {
+            println!("{}", "no issues found".green());
+        }
+    }
+}
+
+
+/// Verify a package directory's `pkg.stof` manifest: required fields, dependency/registry
+/// references, registry reachability, and duplicate/conflicting dependency declarations.
+/// Used both by `stof check` standalone and automatically before `stof publish`.
+pub(crate) async fn check_package(dir: &str, check_reachability: bool) -> Diagnostics {
+    let mut diagnostics = Diagnostics::default();
+    let pkg_path = format!("{}/pkg.stof", dir);
+
+    let Ok(pkg_doc) = SDoc::file(&pkg_path, "stof") else {
+        diagnostics.error("pkg.stof", "file not found or failed to parse");
+        return diagnostics;
+    };
+
+    if SField::field(&pkg_doc.graph, "root.name", '.', None).is_none() {
+        diagnostics.error("root.name", "missing required field");
+    }
+    if SField::field(&pkg_doc.graph, "root.version", '.', None).is_none() {
+        diagnostics.error("root.version", "missing required field");
+    }
+
+    license::check_license(dir, &pkg_doc, &mut diagnostics);
+
+    let mut defined_registries = HashSet::new();
+    if let Some(nref) = pkg_doc.graph.node_ref("root/registries", None) {
+        for field in SField::fields(&pkg_doc.graph, &nref) {
+            defined_registries.insert(field.name.clone());
+            if let SVal::Object(registry_nref) = &field.value {
+                if let Some(url_field) = SField::field(&pkg_doc.graph, "registry.url", '.', Some(registry_nref)) {
+                    if check_reachability {
+                        let url = url_field.to_string();
+                        if let Err(error) = reqwest::Client::new().head(&url).send().await {
+                            diagnostics.warning(&format!("root.registries.{}", field.name), &format!("registry unreachable: {}", error));
+                        }
+                    }
+                } else {
+                    diagnostics.error(&format!("root.registries.{}", field.name), "registry is missing a 'url' field");
+                }
+            }
+        }
+    }
+
+    if let Some(deps_field) = SField::field(&pkg_doc.graph, "root.dependencies", '.', None) {
+        let mut seen = HashSet::new();
+        match &deps_field.value {
+            SVal::Array(vals) => {
+                for (i, val) in vals.iter().enumerate() {
+                    let path = format!("root.dependencies[{}]", i);
+                    match val {
+                        SVal::String(name) => {
+                            if !seen.insert(resolve::parse_dep_spec(name).name) {
+                                diagnostics.warning(&path, &format!("duplicate dependency declaration for '{}'", name));
+                            }
+                        },
+                        SVal::Object(nref) => {
+                            let name_field = SField::field(&pkg_doc.graph, "name", '.', Some(nref));
+                            let Some(name_field) = name_field else {
+                                diagnostics.error(&path, "dependency object is missing a 'name' field");
+                                continue;
+                            };
+                            let name = name_field.to_string();
+                            if !seen.insert(resolve::parse_dep_spec(&name).name) {
+                                diagnostics.warning(&path, &format!("duplicate dependency declaration for '{}'", name));
+                            }
+                            if let Some(registry_field) = SField::field(&pkg_doc.graph, "registry", '.', Some(nref)) {
+                                let registry_name = registry_field.to_string();
+                                if !defined_registries.contains(&registry_name) {
+                                    diagnostics.error(&path, &format!("dependency '{}' references undefined registry '{}'", name, registry_name));
+                                }
+                            }
+                        },
+                        _ => {
+                            diagnostics.error(&path, "dependency entry must be a string or an object with a 'name' field");
+                        }
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    diagnostics
+}
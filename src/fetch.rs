@@ -0,0 +1,207 @@
+//
+// Copyright 2024 Formata, Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::{collections::BTreeMap, fs};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use stof::{model::StofPackageFormat, SDoc, SField, SVal};
+use crate::{auth, lock::sha256_hex, resolve};
+
+
+/// One resolved dependency entry written to `pkg.lock`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct PkgLockEntry {
+    pub version: String,
+    pub url: String,
+    pub sha256: String,
+}
+
+/// `pkg.lock` - the resolved set of `root.dependencies`, distinct from `stof.lock` (which
+/// tracks `stof add` workspace installs): this lockfile exists purely to make `stof fetch`
+/// reproducible, mirroring `cargo generate-lockfile` + `cargo fetch`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct PkgLock {
+    #[serde(default)]
+    pub packages: BTreeMap<String, PkgLockEntry>,
+}
+impl PkgLock {
+    /// Load `pkg.lock` from the workspace root, returning an empty lock if none exists yet.
+    pub fn load(pkg_dir: &str) -> Self {
+        let path = Self::path(pkg_dir);
+        if let Ok(contents) = fs::read_to_string(&path) {
+            if let Ok(lock) = serde_json::from_str(&contents) {
+                return lock;
+            }
+        }
+        Self::default()
+    }
+
+    /// Write `pkg.lock` back to the workspace root.
+    pub fn save(&self, pkg_dir: &str) {
+        let path = Self::path(pkg_dir);
+        if let Ok(contents) = serde_json::to_string_pretty(self) {
+            let _ = fs::write(&path, contents);
+        }
+    }
+
+    fn path(pkg_dir: &str) -> String {
+        format!("{}/pkg.lock", pkg_dir)
+    }
+
+    /// Record (or overwrite) an entry after a successful, verified fetch.
+    pub fn record(&mut self, name: &str, version: &str, url: &str, sha256: &str) {
+        self.packages.insert(name.to_owned(), PkgLockEntry { version: version.to_owned(), url: url.to_owned(), sha256: sha256.to_owned() });
+    }
+
+    /// Look up a previously locked entry by package name.
+    pub fn get(&self, name: &str) -> Option<&PkgLockEntry> {
+        self.packages.get(name)
+    }
+}
+
+
+/// Local cache directory a fetched dependency is unzipped into, ready for `file_import`.
+fn cache_dir(pkg_dir: &str, name: &str) -> String {
+    format!("{}/.stof/fetch/{}", pkg_dir, name.trim_start_matches('@'))
+}
+
+/// Resolve and fetch every `root.dependencies` entry of `pkg_dir`'s manifest: look up each
+/// dependency's registry (reusing the `registry.url` lookup pattern from `publish_to_registry`),
+/// download its archive, verify it against `pkg.lock` (recording a fresh entry the first time
+/// it's fetched), and unzip it into a local cache directory ready for `file_import`. Subsequent
+/// runs honor the lockfile: a dependency with a locked entry whose cache directory already
+/// exists skips the network entirely; the cached files are not re-hashed against the lock.
+pub(crate) async fn fetch_packages(pkg_dir: &str, username: Option<String>, password: Option<String>) {
+    let pkg_path = format!("{}/pkg.stof", pkg_dir);
+    let Ok(pkg_doc) = SDoc::file(&pkg_path, "stof") else {
+        println!("{}: {}", "fetch error".red(), "pkg.stof file not found".italic().dimmed());
+        return;
+    };
+
+    let Some(deps_field) = SField::field(&pkg_doc.graph, "root.dependencies", '.', None) else {
+        println!("{}", "no dependencies declared in pkg.stof".dimmed());
+        return;
+    };
+
+    let mut deps: Vec<(String, Option<String>)> = Vec::new();
+    if let SVal::Array(vals) = &deps_field.value {
+        for val in vals {
+            match val {
+                SVal::String(raw) => deps.push((raw.clone(), None)),
+                SVal::Object(nref) => {
+                    if let Some(name_field) = SField::field(&pkg_doc.graph, "name", '.', Some(nref)) {
+                        let registry = SField::field(&pkg_doc.graph, "registry", '.', Some(nref)).map(|f| f.to_string());
+                        deps.push((name_field.to_string(), registry));
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    let mut lock = PkgLock::load(pkg_dir);
+    let client = reqwest::Client::new();
+    for (raw, registry_name) in deps {
+        let spec = resolve::parse_dep_spec(&raw);
+
+        let mut reg = None;
+        if let Some(reg_name) = &registry_name {
+            let path = format!("root.registries.{}", reg_name);
+            if let Some(field) = SField::field(&pkg_doc.graph, &path, '.', None) {
+                if let SVal::Object(nref) = &field.value {
+                    reg = Some(nref.clone());
+                }
+            }
+        } else if let Some(nref) = pkg_doc.graph.node_ref("root/registries", None) {
+            for field in SField::fields(&pkg_doc.graph, &nref) {
+                if let SVal::Object(nref) = &field.value {
+                    if reg.is_none() || field.attributes.contains_key("default") {
+                        reg = Some(nref.clone());
+                    }
+                }
+            }
+        }
+
+        let Some(reg) = reg else {
+            println!("{}: {} {}", "fetch error".red(), spec.name.blue(), "registry not found - make sure one is defined in your 'pkg.stof' file".italic().dimmed());
+            continue;
+        };
+        let Some(url_field) = SField::field(&pkg_doc.graph, "registry.url", '.', Some(&reg)) else {
+            println!("{}: {}", "fetch error".red(), "registry URL not found".italic().dimmed());
+            continue;
+        };
+        let registry_url = url_field.to_string();
+        let download = spec.name.trim_start_matches('@').to_owned();
+        let outdir = cache_dir(pkg_dir, &spec.name);
+
+        if lock.get(&spec.name).is_some() && fs::metadata(&outdir).is_ok() {
+            println!("{} {} {}", "fetched".green(), spec.name.blue(), "(cached)".dimmed());
+            continue;
+        }
+
+        let url = format!("{}/registry/{}", registry_url, download);
+        let headers = auth::auth_headers(&registry_url, &username, &password);
+        match client.get(&url).headers(headers).send().await {
+            Ok(response) if response.status().is_success() => {
+                let server_checksum = response.headers().get("X-Stof-Checksum").and_then(|v| v.to_str().ok()).map(|s| s.to_owned());
+                match response.bytes().await {
+                    Ok(bytes) => {
+                        let digest = sha256_hex(&bytes);
+                        if let Some(expected) = &server_checksum {
+                            if *expected != digest {
+                                println!("{}: {} {}", "fetch error".red(), spec.name.blue(), "checksum mismatch against the registry's X-Stof-Checksum header - refusing to unpack a tampered or corrupted package".italic().dimmed());
+                                continue;
+                            }
+                        }
+                        if let Some(locked) = lock.get(&spec.name) {
+                            if locked.sha256 != digest {
+                                println!("{}: {} {}", "fetch error".red(), spec.name.blue(), "checksum mismatch against pkg.lock - refusing to unpack a tampered or corrupted package".italic().dimmed());
+                                continue;
+                            }
+                        }
+
+                        let _ = fs::create_dir_all(&outdir);
+                        let zip_path = format!("{}.pkg", outdir);
+                        if fs::write(&zip_path, &bytes).is_ok() {
+                            StofPackageFormat::unzip_file(&zip_path, &outdir);
+                            let _ = fs::remove_file(&zip_path);
+                        }
+
+                        let version = package_version(&outdir);
+                        lock.record(&spec.name, &version, &registry_url, &digest);
+                        lock.save(pkg_dir);
+                        println!("{} {}", "fetched".green(), spec.name.blue());
+                    },
+                    Err(error) => println!("{}: {}", "fetch error".red(), error.to_string().italic().dimmed()),
+                }
+            },
+            Ok(response) => println!("{}: {} {}", "fetch error".red(), spec.name.blue(), response.status().as_str().italic().dimmed()),
+            Err(error) => println!("{}: {}", "fetch send error".red(), error.to_string().italic().dimmed()),
+        }
+    }
+}
+
+/// Read back the "root.version" field of a freshly unzipped dependency, used to populate
+/// its `pkg.lock` entry.
+fn package_version(outdir: &str) -> String {
+    let pkg_path = format!("{}/pkg.stof", outdir);
+    if let Ok(pkg_doc) = SDoc::file(&pkg_path, "stof") {
+        if let Some(version_field) = SField::field(&pkg_doc.graph, "root.version", '.', None) {
+            return version_field.to_string();
+        }
+    }
+    String::default()
+}
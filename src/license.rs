@@ -0,0 +1,254 @@
+//
+// Copyright 2024 Formata, Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::collections::HashSet;
+use stof::{SDoc, SField, SVal};
+use crate::{check::Diagnostics, fetch::PkgLock, lock::StofLock};
+
+/// A parsed SPDX license expression: identifiers joined by AND/OR, with optional WITH exceptions.
+#[derive(Debug, Clone)]
+enum Expr {
+    Id(String),
+    With(Box<Expr>, String),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+fn lex(expr: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for c in expr.chars() {
+        if c == '(' || c == ')' {
+            if !current.is_empty() { tokens.push(current.clone()); current.clear(); }
+            tokens.push(c.to_string());
+        } else if c.is_whitespace() {
+            if !current.is_empty() { tokens.push(current.clone()); current.clear(); }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() { tokens.push(current); }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|s| s.as_str())
+    }
+
+    fn advance(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_expr(&mut self) -> Option<Expr> {
+        self.parse_or()
+    }
+
+    fn parse_or(&mut self) -> Option<Expr> {
+        let mut left = self.parse_and()?;
+        while self.peek().is_some_and(|t| t.eq_ignore_ascii_case("OR")) {
+            self.advance();
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Some(left)
+    }
+
+    fn parse_and(&mut self) -> Option<Expr> {
+        let mut left = self.parse_with()?;
+        while self.peek().is_some_and(|t| t.eq_ignore_ascii_case("AND")) {
+            self.advance();
+            let right = self.parse_with()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Some(left)
+    }
+
+    fn parse_with(&mut self) -> Option<Expr> {
+        let atom = self.parse_atom()?;
+        if self.peek().is_some_and(|t| t.eq_ignore_ascii_case("WITH")) {
+            self.advance();
+            let exception = self.advance()?;
+            return Some(Expr::With(Box::new(atom), exception));
+        }
+        Some(atom)
+    }
+
+    fn parse_atom(&mut self) -> Option<Expr> {
+        match self.advance()?.as_str() {
+            "(" => {
+                let inner = self.parse_expr()?;
+                if self.peek() == Some(")") {
+                    self.advance();
+                }
+                Some(inner)
+            },
+            id => Some(Expr::Id(id.to_string())),
+        }
+    }
+}
+
+/// Tokenize and parse an SPDX license expression, ex. "MIT OR Apache-2.0".
+fn parse_expression(expr: &str) -> Option<Expr> {
+    let tokens = lex(expr);
+    if tokens.is_empty() {
+        return None;
+    }
+    Parser { tokens, pos: 0 }.parse_expr()
+}
+
+/// An `OR` node is satisfied if any operand is in the set; an `AND` node requires all operands
+/// in the set. A `WITH` exception doesn't affect membership here - only the base id does.
+fn any_id_in(expr: &Expr, set: &HashSet<String>) -> bool {
+    match expr {
+        Expr::Id(id) => set.contains(id),
+        Expr::With(inner, _) => any_id_in(inner, set),
+        Expr::And(left, right) => any_id_in(left, set) && any_id_in(right, set),
+        Expr::Or(left, right) => any_id_in(left, set) || any_id_in(right, set),
+    }
+}
+
+/// Look up a per-dependency (name+version) clarification recorded in the package being
+/// checked, forcing an expression when a dependency's own declaration is missing or wrong,
+/// ex. `root.license.clarifications."@formata/math@1.0.0" = "MIT"`. This always reads
+/// `root_pkg_doc` - the manifest of the package doing the auditing - never the dependency's
+/// own manifest: you can't edit a third party's `pkg.stof` to force its license, the same
+/// reason cargo-deny's `clarify` lives in the consuming project's config, not the crate's.
+fn clarified_expression(root_pkg_doc: &SDoc, name: &str, version: &str) -> Option<String> {
+    let nref = root_pkg_doc.graph.node_ref("root/license/clarifications", None)?;
+    let key = format!("{}@{}", name, version);
+    // matched by exact field name: the key itself contains '.' (ex. "1.0.0"), so it can't be
+    // looked up as a '.'-separated field path
+    SField::fields(&root_pkg_doc.graph, &nref).into_iter().find(|f| f.name == key).map(|f| f.to_string())
+}
+
+fn read_id_set(pkg_doc: &SDoc, field_path: &str) -> HashSet<String> {
+    let mut set = HashSet::new();
+    if let Some(field) = SField::field(&pkg_doc.graph, field_path, '.', None) {
+        if let SVal::Array(vals) = &field.value {
+            for val in vals {
+                if let SVal::String(id) = val {
+                    set.insert(id.clone());
+                }
+            }
+        }
+    }
+    set
+}
+
+/// Parse `expression` and check it against the `allow`/`deny` id sets, recording any problem
+/// under `field_path`. Shared between the package's own `root.license` and every transitive
+/// dependency's declared license, so both are held to the same gate.
+fn check_expression(field_path: &str, expression: &str, allow: &HashSet<String>, deny: &HashSet<String>, diagnostics: &mut Diagnostics) {
+    let Some(parsed) = parse_expression(expression) else {
+        diagnostics.error(field_path, &format!("'{}' is not a valid SPDX license expression", expression));
+        return;
+    };
+
+    if !deny.is_empty() && any_id_in(&parsed, deny) {
+        diagnostics.error(field_path, &format!("'{}' matches a denied license", expression));
+        return;
+    }
+
+    if !allow.is_empty() && !any_id_in(&parsed, allow) {
+        diagnostics.error(field_path, &format!("'{}' is not in the allowed license list", expression));
+    }
+}
+
+/// The declared license expression for the dependency unpacked at `pkg_dir`: a clarification
+/// recorded in `root_pkg_doc` takes precedence over the dependency's own `root.license` field.
+/// Returns `None` if neither is present - there is no license-text detection, only explicit
+/// declarations.
+fn declared_expression(root_pkg_doc: &SDoc, pkg_dir: &str, name: &str) -> Option<String> {
+    let pkg_path = format!("{}/pkg.stof", pkg_dir);
+    let pkg_doc = SDoc::file(&pkg_path, "stof").ok()?;
+    let version = SField::field(&pkg_doc.graph, "root.version", '.', None).map(|f| f.to_string()).unwrap_or_default();
+    clarified_expression(root_pkg_doc, name, &version)
+        .or_else(|| SField::field(&pkg_doc.graph, "root.license", '.', None).map(|f| f.to_string()))
+}
+
+/// Record a missing license expression at `field_path`: an error if this package actually
+/// opted into the gate (`root.license.allow`/`deny` declared), otherwise a warning - an
+/// unconfigured gate can't tell a problem from a package that simply predates this check.
+fn report_missing_license(field_path: &str, allow: &HashSet<String>, deny: &HashSet<String>, diagnostics: &mut Diagnostics) {
+    if allow.is_empty() && deny.is_empty() {
+        diagnostics.warning(field_path, "no declared license for this package");
+    } else {
+        diagnostics.error(field_path, "no declared license, but root.license.allow/deny is configured");
+    }
+}
+
+/// Check every transitive dependency already resolved into `stof.lock` (`stof add`, chunk0-1)
+/// and `pkg.lock` (`stof fetch`, chunk1-5) against the same `allow`/`deny` gate as the root
+/// package - these lockfiles are the flat, already-resolved dependency graph, so there's no
+/// need to re-walk `root.dependencies` or re-unzip anything ourselves.
+fn check_dependency_licenses(dir: &str, root_pkg_doc: &SDoc, allow: &HashSet<String>, deny: &HashSet<String>, diagnostics: &mut Diagnostics) {
+    let mut checked = HashSet::new();
+
+    for name in StofLock::load(dir).packages.keys() {
+        checked.insert(name.clone());
+        let outdir = format!("{}/__stof__/{}", dir, name.trim_start_matches('@'));
+        let field_path = format!("dependencies.{}.license", name);
+        match declared_expression(root_pkg_doc, &outdir, name) {
+            Some(expression) => check_expression(&field_path, &expression, allow, deny, diagnostics),
+            None => report_missing_license(&field_path, allow, deny, diagnostics),
+        }
+    }
+
+    for name in PkgLock::load(dir).packages.keys() {
+        if !checked.insert(name.clone()) {
+            continue;
+        }
+        let outdir = format!("{}/.stof/fetch/{}", dir, name.trim_start_matches('@'));
+        let field_path = format!("dependencies.{}.license", name);
+        match declared_expression(root_pkg_doc, &outdir, name) {
+            Some(expression) => check_expression(&field_path, &expression, allow, deny, diagnostics),
+            None => report_missing_license(&field_path, allow, deny, diagnostics),
+        }
+    }
+}
+
+/// Validate `root.license` - and every transitive dependency already resolved into
+/// `stof.lock`/`pkg.lock` - against the allow/deny SPDX expressions declared under
+/// `root.license.allow` / `root.license.deny`, folding the result into the shared
+/// `stof check` diagnostics rather than a separate report - this check runs as part of
+/// the same manifest verification pass as everything else in `check.rs`. A missing license
+/// is only an error once a package opts into the gate by declaring `allow`/`deny`; otherwise
+/// it's a warning, so this check doesn't break every pre-existing package that never declared
+/// a license.
+pub(crate) fn check_license(dir: &str, pkg_doc: &SDoc, diagnostics: &mut Diagnostics) {
+    let name = SField::field(&pkg_doc.graph, "root.name", '.', None).map(|f| f.to_string()).unwrap_or_else(|| dir.to_owned());
+    let version = SField::field(&pkg_doc.graph, "root.version", '.', None).map(|f| f.to_string()).unwrap_or_default();
+
+    let expression = clarified_expression(pkg_doc, &name, &version)
+        .or_else(|| SField::field(&pkg_doc.graph, "root.license", '.', None).map(|f| f.to_string()));
+
+    let deny = read_id_set(pkg_doc, "root.license.deny");
+    let allow = read_id_set(pkg_doc, "root.license.allow");
+
+    match expression {
+        Some(expression) => check_expression("root.license", &expression, &allow, &deny, diagnostics),
+        None => report_missing_license("root.license", &allow, &deny, diagnostics),
+    }
+
+    check_dependency_licenses(dir, pkg_doc, &allow, &deny, diagnostics);
+}
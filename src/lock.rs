@@ -0,0 +1,99 @@
+//
+// Copyright 2024 Formata, Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::{collections::BTreeMap, fs};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+
+/// A single resolved & locked package entry, recorded after a successful download.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LockEntry {
+    pub version: String,
+    /// Digest of the raw downloaded `.pkg` zip bytes - checked against a fresh download
+    /// before it's unzipped to disk.
+    pub sha256: String,
+    /// Digest of the concatenated, already-unzipped tree (as `read_pkg_dir_bytes` computes
+    /// it) - checked against the `__stof__/` cache to decide whether a fetch can be skipped.
+    /// Necessarily different from `sha256`: one is over compressed zip bytes, the other over
+    /// decompressed file contents.
+    #[serde(rename = "tree-sha256")]
+    pub tree_sha256: String,
+    #[serde(rename = "registry-url")]
+    pub registry_url: String,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+
+/// Workspace lockfile (`stof.lock`), keyed by package name.
+/// Mirrors the role of `Cargo.lock`: pinning exactly what was resolved & installed
+/// so repeat installs are reproducible and tamper-evident.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct StofLock {
+    #[serde(default)]
+    pub packages: BTreeMap<String, LockEntry>,
+}
+impl StofLock {
+    /// Load the lockfile from the workspace root, returning an empty lock if none exists yet.
+    pub fn load(pkg_dir: &str) -> Self {
+        let path = Self::path(pkg_dir);
+        if let Ok(contents) = fs::read_to_string(&path) {
+            if let Ok(lock) = serde_json::from_str(&contents) {
+                return lock;
+            }
+        }
+        Self::default()
+    }
+
+    /// Write the lockfile back to the workspace root.
+    pub fn save(&self, pkg_dir: &str) {
+        let path = Self::path(pkg_dir);
+        if let Ok(contents) = serde_json::to_string_pretty(self) {
+            let _ = fs::write(&path, contents);
+        }
+    }
+
+    /// Path to `stof.lock` for a given workspace/package directory.
+    fn path(pkg_dir: &str) -> String {
+        format!("{}/stof.lock", pkg_dir)
+    }
+
+    /// Record (or overwrite) an entry after a successful, verified download.
+    pub fn record(&mut self, name: &str, version: &str, sha256: &str, tree_sha256: &str, registry_url: &str, dependencies: Vec<String>) {
+        self.packages.insert(name.to_owned(), LockEntry {
+            version: version.to_owned(),
+            sha256: sha256.to_owned(),
+            tree_sha256: tree_sha256.to_owned(),
+            registry_url: registry_url.to_owned(),
+            dependencies,
+        });
+    }
+
+    /// Look up a previously locked entry by package name.
+    pub fn get(&self, name: &str) -> Option<&LockEntry> {
+        self.packages.get(name)
+    }
+}
+
+
+/// Compute the SHA-256 digest of a byte slice, returned as a lowercase hex string.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    let digest = hasher.finalize();
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
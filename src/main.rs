@@ -15,12 +15,24 @@
 //
 
 use std::{collections::HashSet, fs, ops::Deref, path::PathBuf};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use colog::format::CologStyle;
 use colored::Colorize;
 use log::Level;
 use stof::{model::{Field, Graph, StofPackageFormat}, runtime::{Error, Runtime, Val}};
 
+mod add;
+mod archive;
+mod auth;
+mod check;
+mod fetch;
+mod license;
+mod lock;
+mod publish;
+mod remote;
+mod resolve;
+mod serve;
+
 
 pub struct StofCliLogger;
 impl CologStyle for StofCliLogger {
@@ -86,6 +98,20 @@ enum Command {
         /// Optional output file path (.pkg).
         /// Default is <PATH>/out.pkg.
         out: Option<String>,
+
+        /// Print the resolved file set (sorted, with sizes) instead of writing the .pkg.
+        #[arg(short, long)]
+        list: bool,
+
+        /// Archive format to write: "zip" (the default .pkg format) or "gzip" (a
+        /// gzip-compressed tar, better suited to large package trees).
+        #[arg(short, long, default_value = "zip")]
+        format: ArchiveFormat,
+
+        /// Gzip compression level, 0 (store) through 9 (best); ignored for "zip". Default
+        /// mirrors flate2's own default trade-off.
+        #[arg(short, long, default_value_t = 6)]
+        compression: u32,
     },
 
     /// Unpackage a Stof package (.pkg) file into a directory of choice.
@@ -95,6 +121,117 @@ enum Command {
 
         /// Optional output directory (defualts to "stof/<PATH NAME>").
         out: Option<String>,
+
+        /// Expected SHA-256 digest of the archive - verified before it's unzipped to disk.
+        #[arg(long)]
+        checksum: Option<String>,
+    },
+
+    /// Verify a package's pkg.stof manifest, collecting every problem in one pass
+    /// rather than stopping at the first one.
+    Check {
+        /// Path to a directory (with a pkg.stof file).
+        path: Option<String>,
+    },
+
+    /// Publish a package to the registries declared in its pkg.stof "publish" list.
+    Publish {
+        /// Path to a directory (with a pkg.stof file).
+        path: Option<String>,
+
+        /// Skip the round-trip verify (unzip, re-import, run tests) before publishing.
+        #[arg(long)]
+        no_verify: bool,
+    },
+
+    /// Remove a package from the registries declared in its pkg.stof "publish" list.
+    Unpublish {
+        /// Path to a directory (with a pkg.stof file).
+        path: Option<String>,
+    },
+
+    /// Resolve and download the packages declared in "root.dependencies", verifying each
+    /// against "pkg.lock" and unpacking it into a local cache ready for "file_import".
+    Fetch {
+        /// Path to a directory (with a pkg.stof file).
+        path: Option<String>,
+
+        #[arg(short, long)]
+        username: Option<String>,
+
+        #[arg(short, long)]
+        password: Option<String>,
+    },
+
+    /// Add a stof package (and its transitive dependencies) to this workspace.
+    Add {
+        /// Package name to add, ex. "@formata/math".
+        package: String,
+
+        /// Registry name to add from (defaults to the package's default/only registry).
+        #[arg(short, long)]
+        registry: Option<String>,
+
+        /// Max number of concurrent dependency downloads (default: available parallelism).
+        #[arg(short, long)]
+        jobs: Option<usize>,
+
+        #[arg(short, long)]
+        username: Option<String>,
+
+        #[arg(short, long)]
+        password: Option<String>,
+    },
+
+    /// Exchange credentials for a scoped access token and cache it locally.
+    Login {
+        /// Registry address, ex. "https://registry.example.com".
+        address: String,
+
+        /// Username to authenticate with.
+        #[arg(short, long)]
+        username: String,
+
+        /// Password to authenticate with.
+        #[arg(short, long)]
+        password: String,
+    },
+
+    /// Remove a locally cached access token for a registry address.
+    Logout {
+        /// Registry address, ex. "https://registry.example.com".
+        address: String,
+    },
+
+    /// Host and manage a local package registry.
+    Registry {
+        #[command(subcommand)]
+        command: RegistryCommand,
+    },
+}
+
+
+/// Archive format written by `stof pkg`.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ArchiveFormat {
+    /// The default `.pkg` zip format, read by `Unpkg`/`Add`/`Fetch`.
+    Zip,
+    /// A gzip-compressed tar, better suited to large package trees at the cost of
+    /// compatibility with the rest of the toolchain's zip-based unpacking.
+    Gzip,
+}
+
+#[derive(Subcommand, Debug)]
+enum RegistryCommand {
+    /// Stand up a local registry + exec server for offline/air-gapped publish & add workflows.
+    Serve {
+        /// Address to bind to, ex. "127.0.0.1:8080".
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        addr: String,
+
+        /// Directory to serve hosted packages from.
+        #[arg(short, long, default_value = "./registry")]
+        dir: String,
     },
 }
 
@@ -170,7 +307,7 @@ fn main() {
                 }
             }
         },
-        Command::Pkg { path, out } => {
+        Command::Pkg { path, out, list, format, compression } => {
             let mut dir = ".".to_string();
             if let Some(path) = path {
                 dir = path;
@@ -250,13 +387,36 @@ fn main() {
                 }
             }
 
-            if let Some(path) = StofPackageFormat::create_package_file(&dir, &out_path, &included, &excluded) {
-                println!("{} {}", "created".green(), path.blue());
-            } else {
-                log::error!("{}", "pkg creation error".red());
+            let files = list_package_files(&dir, &included, &excluded);
+
+            if list {
+                let mut total = 0u64;
+                for (path, size) in &files {
+                    println!("{} {}", format!("{size} bytes").dimmed(), path.blue());
+                    total += size;
+                }
+                println!("{} {} {} {}", "total:".green(), files.len(), "files,".green(), format!("{total} bytes").green());
+                return;
+            }
+
+            match format {
+                ArchiveFormat::Zip => {
+                    if let Some(path) = StofPackageFormat::create_package_file(&dir, &out_path, &included, &excluded) {
+                        println!("{} {}", "created".green(), path.blue());
+                    } else {
+                        log::error!("{}", "pkg creation error".red());
+                    }
+                },
+                ArchiveFormat::Gzip => {
+                    if let Some(path) = archive::create_gzip_tar(&dir, &out_path, compression, &files) {
+                        println!("{} {}", "created".green(), path.blue());
+                    } else {
+                        log::error!("{}", "pkg creation error".red());
+                    }
+                },
             }
         },
-        Command::Unpkg { mut path, out } => {
+        Command::Unpkg { mut path, out, checksum } => {
             if !path.contains('.') {
                 path = format!("{path}.pkg");
             }
@@ -269,11 +429,106 @@ fn main() {
                 stem = stem.replace('.', "_");
                 dir = format!("./stof/{stem}");
             }
-            let _ = fs::create_dir_all(&dir);
 
+            if let Some(expected) = checksum {
+                match fs::read(&path) {
+                    Ok(bytes) => {
+                        let digest = lock::sha256_hex(&bytes);
+                        if digest != expected {
+                            log::error!("{}: {}", "unpkg error".red(), "checksum mismatch - refusing to unpack a tampered or corrupted archive".italic().dimmed());
+                            return;
+                        }
+                    },
+                    Err(error) => {
+                        log::error!("{}: {}", "unpkg error".red(), error.to_string().italic().dimmed());
+                        return;
+                    }
+                }
+            }
+
+            let _ = fs::create_dir_all(&dir);
             StofPackageFormat::unzip_file(&path, &dir);
             println!("{} {}", "unpacked".green(), path.blue());
         },
+        Command::Check { path } => {
+            let dir = path.unwrap_or_else(|| ".".to_string());
+            let diagnostics = block_on(check::check_package(&dir, true));
+            diagnostics.print();
+            if diagnostics.has_errors() {
+                std::process::exit(1);
+            }
+        },
+        Command::Publish { path, no_verify } => {
+            let dir = path.unwrap_or_else(|| ".".to_string());
+            block_on(publish::publish_package(&dir, !no_verify));
+        },
+        Command::Unpublish { path } => {
+            let dir = path.unwrap_or_else(|| ".".to_string());
+            block_on(publish::unpublish_package(&dir));
+        },
+        Command::Fetch { path, username, password } => {
+            let dir = path.unwrap_or_else(|| ".".to_string());
+            block_on(fetch::fetch_packages(&dir, username, password));
+        },
+        Command::Add { package, registry, jobs, username, password } => {
+            block_on(add::add_package(".", &package, registry, false, username, password, jobs));
+        },
+        Command::Login { address, username, password } => {
+            block_on(auth::login(&address, &username, &password));
+        },
+        Command::Logout { address } => {
+            auth::logout(&address);
+        },
+        Command::Registry { command } => match command {
+            RegistryCommand::Serve { addr, dir } => {
+                block_on(serve::serve(&addr, &dir));
+            },
+        },
+    }
+}
+
+
+/// Run a future to completion on a fresh single-threaded Tokio runtime, for the handful
+/// of commands (ex. `login`) that need an async HTTP call but don't warrant an async `main`.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start async runtime")
+        .block_on(future)
+}
+
+
+/// Resolve a package directory's include/exclude patterns into the sorted file set that
+/// would be packaged, with each file's size - used by `stof pkg --list` to preview a
+/// package without writing it, mirroring `cargo package --list`.
+fn list_package_files(dir: &str, included: &HashSet<String>, excluded: &HashSet<String>) -> Vec<(String, u64)> {
+    let include_patterns: Vec<regex::Regex> = included.iter().filter_map(|p| regex::Regex::new(p).ok()).collect();
+    let exclude_patterns: Vec<regex::Regex> = excluded.iter().filter_map(|p| regex::Regex::new(p).ok()).collect();
+
+    let mut files = Vec::new();
+    collect_package_files(dir, dir, &include_patterns, &exclude_patterns, &mut files);
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files
+}
+
+fn collect_package_files(root: &str, current: &str, include: &[regex::Regex], exclude: &[regex::Regex], out: &mut Vec<(String, u64)>) {
+    let Ok(entries) = fs::read_dir(current) else { return; };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+        if path.is_dir() {
+            collect_package_files(root, path.to_str().unwrap_or_default(), include, exclude, out);
+        } else {
+            if exclude.iter().any(|pattern| pattern.is_match(&relative)) {
+                continue;
+            }
+            if !include.is_empty() && !include.iter().any(|pattern| pattern.is_match(&relative)) {
+                continue;
+            }
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            out.push((relative, size));
+        }
     }
 }
 
@@ -19,6 +19,9 @@ use bytes::Bytes;
 use colored::Colorize;
 use stof::{pkg::PKG, SDoc, SField, SNodeRef, SVal};
 use tokio::{sync::Mutex, task::JoinSet};
+use crate::auth;
+use crate::check;
+use crate::lock::sha256_hex;
 
 
 /// Create a temp zip (pkg) file for a given directory.
@@ -160,8 +163,16 @@ pub(crate) async fn create_temp_pkg_zip(dir: &str) -> Option<String> {
 }
 
 
-/// Publish a stof package to registries.
-pub(crate) async fn publish_package(dir: &str) {
+/// Publish a stof package to registries. Unless `verify` is `false` (`--no-verify`), the
+/// built archive is round-tripped - unzipped, re-imported, and tested - before it ships.
+pub(crate) async fn publish_package(dir: &str, verify: bool) {
+    let diagnostics = check::check_package(dir, false).await;
+    diagnostics.print();
+    if diagnostics.has_errors() {
+        println!("{}: {}", "publish error".red(), "blocked by the diagnostics above - run 'stof check' for details".italic().dimmed());
+        return;
+    }
+
     let pkg_path = format!("{}/pkg.stof", dir);
     if let Ok(pkg_doc) = SDoc::file(&pkg_path, "stof") {
         let mut pkg_path = String::default();
@@ -194,6 +205,12 @@ pub(crate) async fn publish_package(dir: &str) {
         }
 
         if let Some(temp_zip_file_path) = create_temp_pkg_zip(dir).await {
+            if verify && !verify_package_archive(&temp_zip_file_path).await {
+                println!("{}: {}", "publish error".red(), "archive failed verification - pass --no-verify to skip this check".italic().dimmed());
+                let _ = fs::remove_file(&temp_zip_file_path);
+                return;
+            }
+
             if let Ok(bytes) = fs::read(&temp_zip_file_path) {
                 let pkg = Arc::new(Mutex::new((pkg_doc, Bytes::from(bytes))));
                 let mut set = JoinSet::new();
@@ -215,9 +232,48 @@ pub(crate) async fn publish_package(dir: &str) {
 }
 
 
+/// Round-trip verify a freshly built package archive before it ships: unzip it into a
+/// scratch directory, re-import it as a fresh graph, and run its `#[test]` functions. This
+/// catches the common failure mode where an `exclude` pattern drops a file that's actually
+/// imported, which would otherwise only surface after a consumer downloads the broken package.
+async fn verify_package_archive(zip_path: &str) -> bool {
+    use stof::model::Graph;
+
+    let Ok(bytes) = fs::read(zip_path) else {
+        println!("{}: {}", "publish verify error".red(), "could not read built archive".italic().dimmed());
+        return false;
+    };
+
+    let pkg_format = PKG::default();
+    let verify_dir = pkg_format.unzip_pkg_bytes("__publish_verify__", &Bytes::from(bytes));
+
+    let mut graph = Graph::default();
+    let result = match graph.file_import("pkg", &verify_dir, None) {
+        Ok(_) => match graph.test(None, true) {
+            Ok(res) => {
+                println!("{} {}", "verify".dimmed(), res.to_string().dimmed());
+                true
+            },
+            Err(error) => {
+                println!("{}: {}", "publish verify error".red(), error.to_string().italic().dimmed());
+                false
+            }
+        },
+        Err(error) => {
+            println!("{}: {}", "publish verify error".red(), error.to_string().italic().dimmed());
+            false
+        }
+    };
+
+    let _ = fs::remove_dir_all(&verify_dir);
+    result
+}
+
+
 /// Publish the package to a specific registry.
 async fn publish_to_registry(pkg: Arc<Mutex<(SDoc, Bytes)>>, registry: SNodeRef, publish_path: String) {
     let mut url = String::default();
+    let mut manifest_token = None;
     let bytes;
     {
         let pkg = pkg.lock().await;
@@ -227,12 +283,37 @@ async fn publish_to_registry(pkg: Arc<Mutex<(SDoc, Bytes)>>, registry: SNodeRef,
         if let Some(url_field) = SField::field(&doc.graph, "registry.url", '.', Some(&registry)) {
             url = url_field.to_string();
         }
+        if let Some(token_field) = SField::field(&doc.graph, "registry.token", '.', Some(&registry)) {
+            manifest_token = Some(token_field.to_string());
+        }
     }
 
     if bytes.len() > 0 && url.len() > 0 {
+        let target_scope = publish_path.split('/').next().unwrap_or_default();
+        if manifest_token.is_none() && !auth::can_modify_scope(&url, target_scope) {
+            println!("{}: {} {}", "publish error".red(), publish_path.blue(), "cached token does not grant modify access for this scope - run 'stof login' with an account that does".italic().dimmed());
+            return;
+        }
+
+        // Prefer an explicit 'registry.token' field in pkg.stof (handy for CI); otherwise
+        // fall back to whatever 'stof login' cached in ~/.stof/credentials for this registry.
+        let headers = match &manifest_token {
+            Some(token) => auth::bearer(token),
+            None => auth::auth_headers(&url, &None, &None),
+        };
         let url = format!("{}/registry/{}", url, publish_path);
+        let mut headers = headers;
+        let digest = sha256_hex(&bytes);
+        if let Ok(value) = digest.parse() {
+            headers.insert("X-Stof-Checksum", value);
+        }
+        if let Ok(value) = bytes.len().to_string().parse() {
+            headers.insert("X-Stof-Checksum-Length", value);
+        }
+
         let client = reqwest::Client::new();
         let res = client.put(&url)
+            .headers(headers)
             .body(bytes)
             .send()
             .await;
@@ -287,8 +368,19 @@ pub(crate) async fn unpublish_package(dir: &str) {
         let client = reqwest::Client::new();
         for registry in publish_registries {
             if let Some(url_field) = SField::field(&pkg_doc.graph, "registry.url", '.', Some(&registry)) {
-                let url = format!("{}/registry/{}", url_field.to_string(), &pkg_path);
-                let res = client.delete(&url).send().await;
+                let registry_url = url_field.to_string();
+                let manifest_token = SField::field(&pkg_doc.graph, "registry.token", '.', Some(&registry)).map(|f| f.to_string());
+                let target_scope = pkg_path.split('/').next().unwrap_or_default();
+                if manifest_token.is_none() && !auth::can_modify_scope(&registry_url, target_scope) {
+                    println!("{}: {} {}", "unpublish error".red(), pkg_path.blue(), "cached token does not grant modify access for this scope".italic().dimmed());
+                    continue;
+                }
+                let headers = match &manifest_token {
+                    Some(token) => auth::bearer(token),
+                    None => auth::auth_headers(&registry_url, &None, &None),
+                };
+                let url = format!("{}/registry/{}", registry_url, &pkg_path);
+                let res = client.delete(&url).headers(headers).send().await;
                 match res {
                     Ok(response) => {
                         let text = response.text().await.unwrap();
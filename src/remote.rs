@@ -20,9 +20,11 @@ use colored::Colorize;
 use http_auth_basic::Credentials;
 use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
 use stof::{SData, SDoc, SFunc};
+use crate::auth;
 use crate::publish::create_temp_pkg_zip;
 
 
+
 /// Execute a stof document or package remotely, parsing/creating it on the remote server.
 pub async fn remote_exec(address: &str, path: &str, username: Option<String>, password: Option<String>) {
     let path_buf;
@@ -37,11 +39,7 @@ pub async fn remote_exec(address: &str, path: &str, username: Option<String>, pa
 
     let url = format!("{}/run", address);
     let client = reqwest::Client::new();
-    let mut headers = HeaderMap::new();
-    if username.is_some() && password.is_some() {
-        let credentials = Credentials::new(&username.unwrap(), &password.unwrap());
-        headers.insert(AUTHORIZATION, credentials.as_http_header().parse().unwrap());
-    }
+    let mut headers = auth::auth_headers(address, &username, &password);
 
     let mut bytes = None;
     if path_buf.is_dir() {
@@ -190,13 +188,14 @@ pub async fn remote_exec_doc(address: &str, doc: &SDoc, username: Option<String>
 /// Need admin permissions on the server, along with the user information to create/set.
 /// Perms: 0b001 - read registry, 0b010 - modify registry, 0b100 - exec
 /// Scope: optional, restricts modification of the registry to a specific top-level scope for a user. Ex. "formata" would allow modification to only @formata/... packages.
+/// Authenticates as the admin the same way every other registry call does: a cached PASETO
+/// token from `auth::login` if one exists for `address`, falling back to Basic credentials
+/// otherwise - see `auth::auth_headers`.
 pub async fn set_remote_user(address: &str, admin_user: &str, admin_pass: &str, user: &str, pass: &str, perms: i64, scope: &str) {
     let url = format!("{}/admin/users", address);
     let payload = format!("username: '{}', password: '{}', perms: {}, scope: '{}'", user, pass, perms, scope);
-    
-    let mut headers = HeaderMap::new();
-    let credentials = Credentials::new(admin_user, admin_pass);
-    headers.insert(AUTHORIZATION, credentials.as_http_header().parse().unwrap());
+
+    let mut headers = auth::auth_headers(address, &Some(admin_user.to_owned()), &Some(admin_pass.to_owned()));
     headers.insert(CONTENT_TYPE, "application/stof".parse().unwrap());
 
     let client = reqwest::Client::new();
@@ -221,13 +220,14 @@ pub async fn set_remote_user(address: &str, admin_user: &str, admin_pass: &str,
 
 /// Remove remote user.
 /// Need admin permissions on the server, along with the username to delete.
+/// Authenticates as the admin the same way every other registry call does: a cached PASETO
+/// token from `auth::login` if one exists for `address`, falling back to Basic credentials
+/// otherwise - see `auth::auth_headers`.
 pub async fn remove_remote_user(address: &str, admin_user: &str, admin_pass: &str, user: &str) {
     let url = format!("{}/admin/users", address);
     let payload = format!("username: '{}'", user);
-    
-    let mut headers = HeaderMap::new();
-    let credentials = Credentials::new(admin_user, admin_pass);
-    headers.insert(AUTHORIZATION, credentials.as_http_header().parse().unwrap());
+
+    let mut headers = auth::auth_headers(address, &Some(admin_user.to_owned()), &Some(admin_pass.to_owned()));
     headers.insert(CONTENT_TYPE, "application/stof".parse().unwrap());
 
     let client = reqwest::Client::new();
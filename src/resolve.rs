@@ -0,0 +1,136 @@
+//
+// Copyright 2024 Formata, Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::{collections::HashMap, sync::Arc};
+use colored::Colorize;
+use semver::{Version, VersionReq};
+use tokio::sync::{Mutex, Notify};
+
+
+/// A parsed `name@version-req` dependency entry, ex. "@formata/math@^1.2" or bare "@formata/math".
+#[derive(Debug, Clone)]
+pub(crate) struct DepSpec {
+    pub name: String,
+    pub req: VersionReq,
+}
+
+/// Parse a dependency string into a package name and semver requirement, defaulting to
+/// "*" (any version) when no requirement is present.
+pub(crate) fn parse_dep_spec(raw: &str) -> DepSpec {
+    if let Some(at) = raw.rfind('@').filter(|&i| i > 0) {
+        let (name, req) = raw.split_at(at);
+        if let Ok(req) = VersionReq::parse(&req[1..]) {
+            return DepSpec { name: name.to_owned(), req };
+        }
+    }
+    DepSpec { name: raw.to_owned(), req: VersionReq::STAR }
+}
+
+/// Outcome of entering a package in the resolution graph, telling the caller whether it
+/// actually needs to hit the network or can be skipped.
+pub(crate) enum Entry {
+    /// Neither resolved nor in flight (never seen, or an earlier fetch failed) - go ahead and fetch it.
+    Fetch,
+    /// Already resolved in this run - de-duplicated, no need to fetch again.
+    AlreadyResolved,
+    /// Another concurrent task is fetching this same package right now - await this, then
+    /// re-check `AlreadyResolved`/`Fetch` rather than racing a duplicate download.
+    InFlight(Arc<Notify>),
+    /// This package is already an ancestor of itself on the current path - cycle, skip it.
+    Cycle,
+}
+
+/// An in-memory dependency graph used to de-duplicate and detect cycles in the transitive
+/// dependencies of an install, recording a single version per package. That version is
+/// whichever one the registry served; version requirements are checked against it after the
+/// download and a conflict is only warned about - they are not used to choose a version.
+/// Safe to drive from multiple concurrently-spawned tasks via `SharedResolver`.
+#[derive(Debug, Default)]
+pub(crate) struct Resolver {
+    resolved: HashMap<String, Version>,
+    requirements: HashMap<String, Vec<VersionReq>>,
+    in_flight: HashMap<String, Arc<Notify>>,
+    order: Vec<String>,
+}
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attempt to enter `name`, recording `req` as one of its requirements and checking
+    /// `ancestors` (the chain of packages currently being resolved on this path) for cycles.
+    pub fn enter(&mut self, name: &str, req: &VersionReq, ancestors: &[String]) -> Entry {
+        if ancestors.iter().any(|a| a == name) {
+            println!("{}: {} {}", "dependency cycle detected".yellow(), name.blue(), "- breaking cycle here".dimmed());
+            return Entry::Cycle;
+        }
+        self.requirements.entry(name.to_owned()).or_default().push(req.clone());
+
+        if self.resolved.contains_key(name) {
+            return Entry::AlreadyResolved;
+        }
+        if let Some(notify) = self.in_flight.get(name) {
+            return Entry::InFlight(notify.clone());
+        }
+        self.in_flight.insert(name.to_owned(), Arc::new(Notify::new()));
+        Entry::Fetch
+    }
+
+    /// Record the concrete version that was actually downloaded for `name`, warning (but not
+    /// failing) if it doesn't satisfy every requirement gathered for it so far - the first
+    /// resolution wins, and requirements entered after it are recorded but not checked.
+    /// Wakes any concurrent tasks that were waiting on this same package.
+    pub fn resolve(&mut self, name: &str, version: &Version) {
+        if let Some(reqs) = self.requirements.get(name) {
+            for req in reqs {
+                if !req.matches(version) {
+                    println!("{}: {} {} {} {}", "dependency conflict".yellow(), name.blue(), version.to_string().dimmed(), "does not satisfy requirement".dimmed(), req.to_string().dimmed());
+                }
+            }
+        }
+        self.resolved.insert(name.to_owned(), version.clone());
+        if !self.order.contains(&name.to_owned()) {
+            self.order.push(name.to_owned());
+        }
+        if let Some(notify) = self.in_flight.remove(name) {
+            notify.notify_waiters();
+        }
+    }
+
+    /// The flat, resolved install order (first-resolved-first), ready to be written to `stof.lock`.
+    pub fn order(&self) -> &[String] {
+        &self.order
+    }
+
+    /// Abort a fetch that was entered via `enter` but never completed with `resolve` (a
+    /// registry miss, network error, or bad checksum): clear its in-flight marker and wake
+    /// any concurrent waiters, who will re-enter and retry rather than hang forever.
+    pub fn fail(&mut self, name: &str) {
+        if let Some(notify) = self.in_flight.remove(name) {
+            notify.notify_waiters();
+        }
+    }
+}
+
+
+/// A `Resolver` shared across the concurrent, recursive install of one package's transitive
+/// dependency tree.
+pub(crate) type SharedResolver = Arc<Mutex<Resolver>>;
+
+/// Start a fresh resolution for a new top-level install.
+pub(crate) fn new_shared() -> SharedResolver {
+    Arc::new(Mutex::new(Resolver::new()))
+}
@@ -0,0 +1,438 @@
+//
+// Copyright 2024 Formata, Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::{collections::HashMap, fs, path::PathBuf, sync::Arc};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse},
+    routing::{get, post},
+    Router,
+};
+use colored::Colorize;
+use http_auth_basic::Credentials;
+use pasetors::{
+    claims::{Claims, ClaimsValidationRules},
+    keys::{AsymmetricKeyPair, AsymmetricPublicKey, AsymmetricSecretKey, Generate},
+    public,
+    version4::V4,
+};
+use serde::{Deserialize, Serialize};
+use stof::{SDoc, SField};
+use tokio::sync::RwLock;
+
+use crate::auth::{PERM_EXEC, PERM_MODIFY, PERM_READ};
+use crate::lock::sha256_hex;
+
+
+/// A single registered registry user - mirrors the perms/scope model already used by
+/// `remote::set_remote_user` on the client side.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RegistryUser {
+    username: String,
+    password: String,
+    perms: i64,
+    scope: String,
+}
+
+/// On-disk user table for this registry instance, stored alongside the hosted packages.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Users {
+    #[serde(default)]
+    users: HashMap<String, RegistryUser>,
+}
+
+struct ServerState {
+    packages_dir: PathBuf,
+    users_path: PathBuf,
+    users: RwLock<Users>,
+    keypair: AsymmetricKeyPair<V4>,
+}
+
+
+/// Stand up a local registry + exec server, serving packages from `packages_dir` and
+/// listening on `bind_addr` (ex. "127.0.0.1:8080"). Mirrors the endpoints the client side
+/// of this CLI already assumes exist: `GET /registry/{name}`, `POST /run`, and the
+/// `/admin/users` management routes.
+pub(crate) async fn serve(bind_addr: &str, packages_dir: &str) {
+    let packages_dir = PathBuf::from(packages_dir);
+    let _ = fs::create_dir_all(&packages_dir);
+    let users_path = packages_dir.join(".stof-registry-users.json");
+
+    let users = if let Ok(contents) = fs::read_to_string(&users_path) {
+        serde_json::from_str(&contents).unwrap_or_default()
+    } else {
+        Users::default()
+    };
+
+    let keypair = load_or_generate_keypair(&packages_dir);
+
+    let state = Arc::new(ServerState {
+        packages_dir,
+        users_path,
+        users: RwLock::new(users),
+        keypair,
+    });
+
+    let app = Router::new()
+        .route("/", get(index))
+        .route("/registry/{*name}", get(get_package).put(put_package).delete(delete_package))
+        .route("/run", post(run))
+        .route("/login", post(login))
+        .route("/admin/users", post(set_user).delete(remove_user))
+        .with_state(state);
+
+    println!("{} {}", "serving registry on".green(), bind_addr.blue());
+    match tokio::net::TcpListener::bind(bind_addr).await {
+        Ok(listener) => {
+            if let Err(error) = axum::serve(listener, app).await {
+                println!("{}: {}", "registry server error".red(), error.to_string().italic().dimmed());
+            }
+        },
+        Err(error) => {
+            println!("{}: {}", "registry bind error".red(), error.to_string().italic().dimmed());
+        }
+    }
+}
+
+
+/// Load this registry's PASETO v4 signing keypair from a file alongside the hosted packages,
+/// generating and persisting a fresh one the first time the server starts against this
+/// `packages_dir` - every token `login` mints is verifiable for as long as this file survives.
+fn load_or_generate_keypair(packages_dir: &PathBuf) -> AsymmetricKeyPair<V4> {
+    let path = packages_dir.join(".stof-registry-key");
+    if let Ok(bytes) = fs::read(&path) {
+        if let Ok(secret) = AsymmetricSecretKey::<V4>::try_from(bytes.as_slice()) {
+            if let Ok(public) = AsymmetricPublicKey::<V4>::try_from(&secret) {
+                return AsymmetricKeyPair { secret, public };
+            }
+        }
+    }
+    let keypair = AsymmetricKeyPair::<V4>::generate().expect("failed to generate PASETO keypair");
+    let _ = fs::write(&path, keypair.secret.as_bytes());
+    keypair
+}
+
+
+/// Mint a signed PASETO v4 public token encoding `user`'s scope/perms claims - this is what
+/// `/login` hands back, and the only thing `authorize`'s Bearer branch ever trusts.
+fn mint_token(keypair: &AsymmetricKeyPair<V4>, user: &RegistryUser) -> Option<String> {
+    let mut claims = Claims::new().ok()?;
+    claims.add_additional("sub", user.username.clone()).ok()?;
+    claims.add_additional("scope", user.scope.clone()).ok()?;
+    claims.add_additional("perms", user.perms).ok()?;
+    public::sign(&keypair.secret, &claims, None, None).ok()
+}
+
+
+/// Verify a Bearer token's PASETO v4 signature against this registry's own public key and
+/// pull the scope/perms claims back out, rather than ever comparing the token to a stored
+/// plaintext password.
+fn verify_token(keypair: &AsymmetricKeyPair<V4>, token: &str) -> Option<(String, i64)> {
+    let rules = ClaimsValidationRules::new();
+    let trusted = public::verify(&keypair.public, token, &rules, None, None).ok()?;
+    let claims: &Claims = trusted.payload_claims()?;
+    let scope = claims.get_claim("scope")?.as_str()?.to_owned();
+    let perms = claims.get_claim("perms")?.as_i64()?;
+    Some((scope, perms))
+}
+
+
+/// Reject any package name that could escape `packages_dir` once joined onto it: no
+/// parent-dir (`..`) components, and nothing rooted/absolute (`PathBuf::join` treats an
+/// absolute second argument as replacing the base entirely, so "/etc/cron.d/x" would
+/// otherwise land outside `packages_dir` too). Only plain path segments are allowed through.
+fn sanitized_package_path(state: &ServerState, name: &str) -> Option<PathBuf> {
+    use std::path::Component;
+    let candidate = PathBuf::from(name);
+    if candidate.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    Some(state.packages_dir.join(format!("{name}.pkg")))
+}
+
+
+/// Path to the sidecar file a package's `X-Stof-Checksum` digest is persisted in, alongside
+/// its `.pkg` bytes.
+fn checksum_sidecar_path(pkg_path: &PathBuf) -> PathBuf {
+    PathBuf::from(format!("{}.sha256", pkg_path.display()))
+}
+
+
+/// Escape the handful of characters that matter when interpolating untrusted text into HTML.
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+
+/// Browsable HTML index of every hosted package - name, size, and modified time - like a
+/// static file server's directory listing.
+async fn index(State(state): State<Arc<ServerState>>) -> Html<String> {
+    let mut rows = String::new();
+    if let Ok(entries) = fs::read_dir(&state.packages_dir) {
+        let mut files: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+        files.sort_by_key(|e| e.path());
+        for entry in files {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("pkg") {
+                continue;
+            }
+            let name = html_escape(&path.file_name().unwrap_or_default().to_string_lossy());
+            let (size, modified) = entry.metadata().map(|m| {
+                let modified = m.modified().ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs().to_string())
+                    .unwrap_or_default();
+                (m.len(), modified)
+            }).unwrap_or((0, String::default()));
+            rows.push_str(&format!(
+                "<tr><td>{name}</td><td>{size} bytes</td><td>{modified}</td></tr>\n"
+            ));
+        }
+    }
+    Html(format!(
+        "<html><head><title>stof registry</title></head><body><h1>stof registry</h1>\
+         <table border=\"1\"><tr><th>name</th><th>size</th><th>modified</th></tr>{rows}</table>\
+         </body></html>"
+    ))
+}
+
+
+/// `GET /registry/{name}` - serve a hosted package's raw zip bytes, gated by the read bit.
+/// Echoes back the `X-Stof-Checksum` digest recorded at publish time (if any), so `add`/
+/// `fetch` can verify the download automatically instead of trusting it blindly.
+async fn get_package(State(state): State<Arc<ServerState>>, Path(name): Path<String>, headers: HeaderMap) -> impl IntoResponse {
+    if !authorize(&state, &headers, &name, PERM_READ).await {
+        return (StatusCode::UNAUTHORIZED, "not authenticated or not authorized to read this scope").into_response();
+    }
+    let Some(path) = sanitized_package_path(&state, &name) else {
+        return (StatusCode::BAD_REQUEST, "invalid package name").into_response();
+    };
+    match fs::read(&path) {
+        Ok(bytes) => {
+            let mut response_headers = HeaderMap::new();
+            if let Ok(checksum) = fs::read_to_string(checksum_sidecar_path(&path)) {
+                if let Ok(value) = checksum.trim().parse() {
+                    response_headers.insert("X-Stof-Checksum", value);
+                }
+            }
+            (StatusCode::OK, response_headers, bytes).into_response()
+        },
+        Err(_) => (StatusCode::NOT_FOUND, "package not found").into_response(),
+    }
+}
+
+
+/// `PUT /registry/{name}` - publish/overwrite a hosted package, gated by the modify bit.
+/// If the request carries an `X-Stof-Checksum` header, it's verified against the uploaded
+/// bytes and persisted in a sidecar file so `get_package` can hand it back out on download.
+async fn put_package(State(state): State<Arc<ServerState>>, Path(name): Path<String>, headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+    if !authorize(&state, &headers, &name, PERM_MODIFY).await {
+        return (StatusCode::UNAUTHORIZED, "not authenticated or not authorized to modify this scope").into_response();
+    }
+    let Some(path) = sanitized_package_path(&state, &name) else {
+        return (StatusCode::BAD_REQUEST, "invalid package name").into_response();
+    };
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+
+    if let Some(checksum) = headers.get("X-Stof-Checksum").and_then(|v| v.to_str().ok()) {
+        if sha256_hex(&body) != checksum {
+            return (StatusCode::BAD_REQUEST, "X-Stof-Checksum does not match the uploaded bytes").into_response();
+        }
+        let _ = fs::write(checksum_sidecar_path(&path), checksum);
+    }
+
+    match fs::write(&path, &body) {
+        Ok(_) => (StatusCode::OK, "published").into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response(),
+    }
+}
+
+
+/// `DELETE /registry/{name}` - unpublish a hosted package, gated by the modify bit.
+async fn delete_package(State(state): State<Arc<ServerState>>, Path(name): Path<String>, headers: HeaderMap) -> impl IntoResponse {
+    if !authorize(&state, &headers, &name, PERM_MODIFY).await {
+        return (StatusCode::UNAUTHORIZED, "not authenticated or not authorized to modify this scope").into_response();
+    }
+    let Some(path) = sanitized_package_path(&state, &name) else {
+        return (StatusCode::BAD_REQUEST, "invalid package name").into_response();
+    };
+    let _ = fs::remove_file(checksum_sidecar_path(&path));
+    match fs::remove_file(&path) {
+        Ok(_) => (StatusCode::OK, "removed").into_response(),
+        Err(error) => (StatusCode::NOT_FOUND, error.to_string()).into_response(),
+    }
+}
+
+
+/// `POST /run` - parse the uploaded document (format taken from `Content-Type`), run it,
+/// and return the resulting document as `bstof`.
+async fn run(headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+    let format = headers.get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("bstof")
+        .to_owned();
+
+    let mut doc = SDoc::default();
+    let mut bytes = body;
+    if let Err(error) = doc.header_import("main", &format, &format, &mut bytes, "") {
+        return (StatusCode::BAD_REQUEST, error.to_string(&doc.graph)).into_response();
+    }
+
+    let _ = doc.run(None, None);
+
+    match doc.export_bytes("main", "bstof", None) {
+        Ok(bytes) => {
+            let mut response_headers = HeaderMap::new();
+            response_headers.insert(header::CONTENT_TYPE, "bstof".parse().unwrap());
+            (StatusCode::OK, response_headers, bytes.to_vec()).into_response()
+        },
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string(&doc.graph)).into_response(),
+    }
+}
+
+
+/// `POST /login` - exchange Basic credentials for a signed PASETO v4 public token encoding
+/// the user's scope/perms, mirroring `auth::login` on the client side: from here on the
+/// client sends `Authorization: Bearer <token>` instead of resending the password.
+async fn login(State(state): State<Arc<ServerState>>, headers: HeaderMap) -> impl IntoResponse {
+    let Some(header_value) = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) else {
+        return (StatusCode::UNAUTHORIZED, "missing Authorization header").into_response();
+    };
+    let Ok(credentials) = Credentials::from_header(header_value.to_owned()) else {
+        return (StatusCode::UNAUTHORIZED, "expected Basic credentials").into_response();
+    };
+
+    let users = state.users.read().await;
+    let Some(user) = users.users.get(&credentials.user_id) else {
+        return (StatusCode::UNAUTHORIZED, "invalid username or password").into_response();
+    };
+    if user.password != credentials.password {
+        return (StatusCode::UNAUTHORIZED, "invalid username or password").into_response();
+    }
+
+    match mint_token(&state.keypair, user) {
+        Some(token) => (StatusCode::OK, token).into_response(),
+        None => (StatusCode::INTERNAL_SERVER_ERROR, "failed to mint token").into_response(),
+    }
+}
+
+
+/// Parse a request body as a bare Stof document (the same `username: 'x', password: 'y', ...`
+/// literal `remote::set_remote_user`/`remote::remove_remote_user` send with
+/// `Content-Type: application/stof`), rather than expecting JSON.
+fn parse_stof_body(body: Bytes) -> Result<SDoc, String> {
+    let mut doc = SDoc::default();
+    let mut bytes = body;
+    doc.header_import("main", "stof", "stof", &mut bytes, "")
+        .map_err(|error| error.to_string(&doc.graph))?;
+    Ok(doc)
+}
+
+/// `POST /admin/users` - create/update a user. Requires an existing admin (modify+exec) user.
+async fn set_user(State(state): State<Arc<ServerState>>, headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+    if !authorize(&state, &headers, "", PERM_MODIFY | PERM_EXEC).await {
+        return (StatusCode::UNAUTHORIZED, "admin permissions required").into_response();
+    }
+    let doc = match parse_stof_body(body) {
+        Ok(doc) => doc,
+        Err(error) => return (StatusCode::BAD_REQUEST, error).into_response(),
+    };
+    let Some(username) = SField::field(&doc.graph, "root.username", '.', None).map(|f| f.to_string()) else {
+        return (StatusCode::BAD_REQUEST, "missing 'username' field").into_response();
+    };
+    let password = SField::field(&doc.graph, "root.password", '.', None).map(|f| f.to_string()).unwrap_or_default();
+    let perms = SField::field(&doc.graph, "root.perms", '.', None)
+        .and_then(|f| f.to_string().parse::<i64>().ok())
+        .unwrap_or_default();
+    let scope = SField::field(&doc.graph, "root.scope", '.', None).map(|f| f.to_string()).unwrap_or_default();
+
+    let mut users = state.users.write().await;
+    users.users.insert(username.clone(), RegistryUser { username: username.clone(), password, perms, scope });
+    save_users(&state.users_path, &users);
+    (StatusCode::OK, format!("set user '{}'", username)).into_response()
+}
+
+
+/// `DELETE /admin/users` - remove a user. Requires an existing admin (modify+exec) user.
+async fn remove_user(State(state): State<Arc<ServerState>>, headers: HeaderMap, body: Bytes) -> impl IntoResponse {
+    if !authorize(&state, &headers, "", PERM_MODIFY | PERM_EXEC).await {
+        return (StatusCode::UNAUTHORIZED, "admin permissions required").into_response();
+    }
+    let doc = match parse_stof_body(body) {
+        Ok(doc) => doc,
+        Err(error) => return (StatusCode::BAD_REQUEST, error).into_response(),
+    };
+    let Some(username) = SField::field(&doc.graph, "root.username", '.', None).map(|f| f.to_string()) else {
+        return (StatusCode::BAD_REQUEST, "missing 'username' field").into_response();
+    };
+
+    let mut users = state.users.write().await;
+    if users.users.remove(&username).is_some() {
+        save_users(&state.users_path, &users);
+        (StatusCode::OK, format!("removed user '{}'", username)).into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "user not found").into_response()
+    }
+}
+
+
+fn save_users(path: &PathBuf, users: &Users) {
+    if let Ok(contents) = serde_json::to_string_pretty(users) {
+        let _ = fs::write(path, contents);
+    }
+}
+
+
+/// Validate the request's Basic credentials against the user table, or its Bearer token
+/// against this registry's signing key (using the token's own claims), requiring
+/// every bit in `required_perms` and (if the matched user has a scope) that `target` starts
+/// with it - mirroring the perms/scope model documented on `remote::set_remote_user`.
+async fn authorize(state: &ServerState, headers: &HeaderMap, target: &str, required_perms: i64) -> bool {
+    let Some(header_value) = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+
+    let users = state.users.read().await;
+    if let Ok(credentials) = Credentials::from_header(header_value.to_owned()) {
+        if let Some(user) = users.users.get(&credentials.user_id) {
+            if user.password == credentials.password {
+                return has_access(user, target, required_perms);
+            }
+        }
+        return false;
+    }
+
+    // Bearer <token> - verified against this registry's own public key (minted by `login`);
+    // the scope/perms live signed inside the token, not in a re-lookup of the user table.
+    if let Some(token) = header_value.strip_prefix("Bearer ") {
+        if let Some((scope, perms)) = verify_token(&state.keypair, token) {
+            let claimed = RegistryUser { username: String::new(), password: String::new(), perms, scope };
+            return has_access(&claimed, target, required_perms);
+        }
+    }
+    false
+}
+
+fn has_access(user: &RegistryUser, target: &str, required_perms: i64) -> bool {
+    if user.perms & required_perms != required_perms {
+        return false;
+    }
+    user.scope.is_empty() || target.is_empty() || target.trim_start_matches('@').starts_with(&user.scope)
+}